@@ -0,0 +1,239 @@
+//! Chat history request/response, modeled on IRC's `CHATHISTORY` capability.
+//!
+//! [`RouteMessage`](crate::world::server::RouteMessage) already relays
+//! [`ChatMessage`](crate::chat::server::ChatMessage)s and
+//! [`GeneralChatMessage`](crate::world::server::GeneralChatMessage) carries a
+//! channel and source id, but there was previously no way to ask a chat/relay
+//! server for past messages in a channel. [`ChatHistoryRequest`] is a variant
+//! of [`ChatMessage`](crate::chat::server::ChatMessage) and
+//! [`ChatHistoryResponse`] is a variant of
+//! [`ChatClientMessage`](crate::chat::client::ChatClientMessage).
+use std::io::{Read, Write};
+use std::io::Result as Res;
+
+use endio::{Deserialize, LERead, LEWrite, Serialize};
+use endio::LittleEndian as LE;
+
+use crate::common::{ObjId, LuWString33};
+use crate::error::LuError;
+
+/// A point in a channel's history to select around.
+///
+/// Hand-implements `Deserialize`/`Serialize`, the same way
+/// [`RouteMessage`](crate::world::server::RouteMessage) does: a `u8`
+/// discriminant (`0` for `MessageId`, `1` for `Timestamp`) followed by the
+/// `u64` value.
+#[derive(Debug, PartialEq)]
+pub enum HistoryBound {
+	/// Identifies a message by its server-assigned id.
+	MessageId(u64),
+	/// Identifies a message by a unix timestamp.
+	Timestamp(u64),
+}
+
+impl<R: Read+LERead> Deserialize<LE, R> for HistoryBound
+	where u8: Deserialize<LE, R>,
+	     u64: Deserialize<LE, R> {
+	fn deserialize(reader: &mut R) -> Res<Self> {
+		let discriminant: u8 = reader.read()?;
+		Ok(match discriminant {
+			0 => Self::MessageId(reader.read()?),
+			1 => Self::Timestamp(reader.read()?),
+			_ => return Err(LuError::UnknownVariant {
+				type_name: "HistoryBound",
+				discriminant: discriminant as u32,
+			}.into()),
+		})
+	}
+}
+
+impl<'a, W: Write+LEWrite> Serialize<LE, W> for &'a HistoryBound
+	where u8: Serialize<LE, W>,
+	     u64: Serialize<LE, W> {
+	fn serialize(self, writer: &mut W) -> Res<()> {
+		match self {
+			HistoryBound::MessageId(id) => {
+				writer.write(0u8)?;
+				writer.write(*id)
+			}
+			HistoryBound::Timestamp(ts) => {
+				writer.write(1u8)?;
+				writer.write(*ts)
+			}
+		}
+	}
+}
+
+/// Selects which slice of a channel's history a [`ChatHistoryRequest`] wants.
+///
+/// Hand-implements `Deserialize`/`Serialize` like [`HistoryBound`]: a `u8`
+/// discriminant (`0` to `3`, in declaration order) followed by the variant's
+/// bounds.
+#[derive(Debug, PartialEq)]
+pub enum HistorySelector {
+	/// The most recent messages.
+	Latest,
+	/// Messages older than `0`.
+	Before(HistoryBound),
+	/// Messages newer than `0`.
+	After(HistoryBound),
+	/// Messages strictly between `0` and `1`.
+	Between(HistoryBound, HistoryBound),
+}
+
+impl<R: Read+LERead> Deserialize<LE, R> for HistorySelector
+	where          u8: Deserialize<LE, R>,
+	     HistoryBound: Deserialize<LE, R> {
+	fn deserialize(reader: &mut R) -> Res<Self> {
+		let discriminant: u8 = reader.read()?;
+		Ok(match discriminant {
+			0 => Self::Latest,
+			1 => Self::Before(reader.read()?),
+			2 => Self::After(reader.read()?),
+			3 => Self::Between(reader.read()?, reader.read()?),
+			_ => return Err(LuError::UnknownVariant {
+				type_name: "HistorySelector",
+				discriminant: discriminant as u32,
+			}.into()),
+		})
+	}
+}
+
+impl<'a, W: Write+LEWrite> Serialize<LE, W> for &'a HistorySelector
+	where              u8: Serialize<LE, W>,
+	  &'a HistoryBound: Serialize<LE, W> {
+	fn serialize(self, writer: &mut W) -> Res<()> {
+		match self {
+			HistorySelector::Latest => writer.write(0u8),
+			HistorySelector::Before(bound) => {
+				writer.write(1u8)?;
+				writer.write(bound)
+			}
+			HistorySelector::After(bound) => {
+				writer.write(2u8)?;
+				writer.write(bound)
+			}
+			HistorySelector::Between(from, to) => {
+				writer.write(3u8)?;
+				writer.write(from)?;
+				writer.write(to)
+			}
+		}
+	}
+}
+
+/// Asks a chat/relay server for past messages in a channel, e.g. to
+/// repopulate a chat window after rejoining.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct ChatHistoryRequest {
+	/// The channel to fetch history for.
+	pub channel: u32,
+	/// Which slice of history to return.
+	pub selector: HistorySelector,
+	/// The maximum number of entries to return.
+	pub max_count: u16,
+}
+
+/// One message in a [`ChatHistoryResponse`].
+#[derive(Debug, PartialEq)]
+pub struct HistoryEntry {
+	pub sender_id: ObjId,
+	pub sender_name: LuWString33,
+	pub timestamp: u64,
+	pub body: String,
+}
+
+/// The reply to a [`ChatHistoryRequest`].
+///
+/// Serializing more than `u16::MAX` entries fails with
+/// [`LuError::InvalidLength`]; the entry count is not checked against the
+/// request's `max_count`.
+#[derive(Debug, PartialEq)]
+pub struct ChatHistoryResponse {
+	pub entries: Vec<HistoryEntry>,
+}
+
+impl<R: Read+LERead> Deserialize<LE, R> for ChatHistoryResponse
+	where         u16: Deserialize<LE, R>,
+	            ObjId: Deserialize<LE, R>,
+	      LuWString33: Deserialize<LE, R>,
+	            u64: Deserialize<LE, R> {
+	fn deserialize(reader: &mut R) -> Res<Self> {
+		let len: u16 = reader.read()?;
+		let mut entries = Vec::with_capacity(len as usize);
+		for _ in 0..len {
+			let sender_id = reader.read()?;
+			let sender_name = reader.read()?;
+			let timestamp = reader.read()?;
+			let body_len: u16 = reader.read()?;
+			let mut bytes = vec![0; (body_len as usize) * 2];
+			Read::read_exact(reader, &mut bytes)?;
+			let units: Vec<u16> = bytes.chunks_exact(2)
+				.map(|b| u16::from_le_bytes([b[0], b[1]]))
+				.collect();
+			let body = String::from_utf16(&units).map_err(|_| LuError::Utf16Decode)?;
+			entries.push(HistoryEntry { sender_id, sender_name, timestamp, body });
+		}
+		Ok(Self { entries })
+	}
+}
+
+impl<'a, W: Write+LEWrite> Serialize<LE, W> for &'a ChatHistoryResponse
+	where           u16: Serialize<LE, W>,
+	        &'a ObjId: Serialize<LE, W>,
+	  &'a LuWString33: Serialize<LE, W>,
+	              u64: Serialize<LE, W> {
+	fn serialize(self, writer: &mut W) -> Res<()> {
+		let entry_count: u16 = self.entries.len().try_into().map_err(|_| LuError::InvalidLength)?;
+		writer.write(entry_count)?;
+		for entry in &self.entries {
+			writer.write(&entry.sender_id)?;
+			writer.write(&entry.sender_name)?;
+			writer.write(entry.timestamp)?;
+			let utf16_body: Vec<u16> = entry.body.encode_utf16().collect();
+			let body_len: u16 = utf16_body.len().try_into().map_err(|_| LuError::InvalidLength)?;
+			writer.write(body_len)?;
+			let bytes: Vec<u8> = utf16_body.iter().flat_map(|u| u.to_le_bytes()).collect();
+			Write::write(writer, &bytes)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn history_bound_round_trips() {
+		for bound in [HistoryBound::MessageId(42), HistoryBound::Timestamp(1_753_600_000)] {
+			let mut bytes = Vec::new();
+			bytes.write(&bound).unwrap();
+			let decoded: HistoryBound = (&bytes[..]).read().unwrap();
+			assert_eq!(decoded, bound);
+		}
+	}
+
+	#[test]
+	fn history_selector_round_trips() {
+		let selectors = [
+			HistorySelector::Latest,
+			HistorySelector::Before(HistoryBound::MessageId(1)),
+			HistorySelector::After(HistoryBound::Timestamp(2)),
+			HistorySelector::Between(HistoryBound::MessageId(1), HistoryBound::Timestamp(2)),
+		];
+		for selector in selectors {
+			let mut bytes = Vec::new();
+			bytes.write(&selector).unwrap();
+			let decoded: HistorySelector = (&bytes[..]).read().unwrap();
+			assert_eq!(decoded, selector);
+		}
+	}
+
+	#[test]
+	fn history_bound_unknown_discriminant_is_rejected() {
+		let bytes = vec![2u8, 0, 0, 0, 0, 0, 0, 0, 0];
+		let result: Res<HistoryBound> = (&bytes[..]).read();
+		assert!(result.is_err());
+	}
+}
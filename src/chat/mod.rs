@@ -0,0 +1,4 @@
+//! Chat server/client message sets.
+pub mod client;
+pub mod history;
+pub mod server;
@@ -0,0 +1,105 @@
+//! All packets a chat server can receive.
+use std::io::{Read, Write};
+use std::io::Result as Res;
+
+use endio::{Deserialize, LERead, LEWrite, Serialize};
+use endio::LittleEndian as LE;
+
+use crate::registry::MessageRegistry;
+use super::history::ChatHistoryRequest;
+
+/// All chat messages a chat server can receive.
+///
+/// Hand-implements `Deserialize`/`Serialize` the same way
+/// [`WorldMessage`](crate::world::server::WorldMessage) does, falling back
+/// to [`Self::Unknown`] for opcodes this crate doesn't model yet.
+#[derive(Debug)]
+#[non_exhaustive]
+#[repr(u32)]
+pub enum ChatMessage {
+	ChatHistoryRequest(ChatHistoryRequest) = 1,
+	/// Catch-all for opcodes not modeled above.
+	///
+	/// [`ChatMessage::deserialize_with_registry`] checks a [`MessageRegistry`]
+	/// for the opcode before falling back to this variant.
+	Unknown { opcode: u32, body: Vec<u8> },
+}
+
+impl ChatMessage {
+	/// Matches `opcode` against the known discriminants above, falling back
+	/// to [`Self::Unknown`] with the already-consumed `body` bytes.
+	fn decode_body(opcode: u32, body: Vec<u8>) -> Res<Self> {
+		Ok(match opcode {
+			1 => Self::ChatHistoryRequest((&body[..]).read()?),
+			_ => Self::Unknown { opcode, body },
+		})
+	}
+
+	/// Deserializes a message, checking `registry` for a handler registered
+	/// for the opcode before falling back to the discriminants modeled by
+	/// this crate and, ultimately, [`Self::Unknown`].
+	pub fn deserialize_with_registry<R: Read+LERead>(reader: &mut R, registry: &MessageRegistry<Self>) -> Res<Self>
+		where u32: Deserialize<LE, R> {
+		let opcode: u32 = reader.read()?;
+		let mut body = Vec::new();
+		reader.read_to_end(&mut body)?;
+		if let Some(result) = registry.decode(opcode, &body) {
+			return result.map_err(Into::into);
+		}
+		Self::decode_body(opcode, body)
+	}
+
+	/// Serializes a message, checking `registry` for a handler registered
+	/// for [`Self::Unknown`]'s opcode instead of writing its captured body
+	/// back out verbatim.
+	pub fn serialize_with_registry<W: Write+LEWrite>(&self, writer: &mut W, registry: &MessageRegistry<Self>) -> Res<()>
+		where u32: Serialize<LE, W> {
+		if let Self::Unknown { opcode, .. } = self {
+			let mut encoded = Vec::new();
+			if let Some(result) = registry.encode(*opcode, self, &mut encoded) {
+				result.map_err(Into::<std::io::Error>::into)?;
+				writer.write(*opcode)?;
+				return writer.write_all(&encoded);
+			}
+		}
+		writer.write(self)
+	}
+}
+
+impl<R: Read+LERead> Deserialize<LE, R> for ChatMessage
+	where u32: Deserialize<LE, R> {
+	fn deserialize(reader: &mut R) -> Res<Self> {
+		let opcode: u32 = reader.read()?;
+		let mut body = Vec::new();
+		reader.read_to_end(&mut body)?;
+		Self::decode_body(opcode, body)
+	}
+}
+
+impl<'a, W: Write+LEWrite> Serialize<LE, W> for &'a ChatMessage
+	where u32: Serialize<LE, W> {
+	fn serialize(self, writer: &mut W) -> Res<()> {
+		match self {
+			ChatMessage::ChatHistoryRequest(msg) => {
+				writer.write(1u32)?;
+				writer.write(msg)
+			}
+			ChatMessage::Unknown { opcode, body } => {
+				writer.write(*opcode)?;
+				writer.write_all(body)
+			}
+		}
+	}
+}
+
+impl crate::registry::RegistryCodec for ChatMessage {
+	fn decode_with_registry<R: Read+LERead>(reader: &mut R, registry: &MessageRegistry<Self>) -> Res<Self>
+		where u32: Deserialize<LE, R> {
+		Self::deserialize_with_registry(reader, registry)
+	}
+
+	fn encode_with_registry<W: Write+LEWrite>(&self, writer: &mut W, registry: &MessageRegistry<Self>) -> Res<()>
+		where u32: Serialize<LE, W> {
+		self.serialize_with_registry(writer, registry)
+	}
+}
@@ -0,0 +1,253 @@
+//! Async framing for reading/writing top-level message enums such as
+//! [`ClientMessage`](crate::world::client::ClientMessage) and
+//! [`WorldMessage`](crate::world::server::WorldMessage) over a Tokio stream.
+//! The RakNet-level `Message` wrappers around them are not framed here.
+//!
+//! Every consumer of this crate otherwise has to pull a length-delimited
+//! packet off a socket by hand before feeding it to
+//! [`Deserialize`](endio::Deserialize). This
+//! module does that once, as a [`tokio_util::codec`] pair plus a small
+//! [`Connection`] wrapper that drives them over an `AsyncRead + AsyncWrite`
+//! stream.
+//!
+//! A serialized message that exceeds `mtu_payload` is broken into RakNet
+//! [`split`](crate::raknet::split) chunks on encode and reassembled on
+//! decode, so the frame itself never needs to carry more than one chunk's
+//! worth of bytes at a time; see [`MessageCodec::with_mtu_payload`]. This is
+//! also why the frame doesn't need a wide top-level length prefix: each
+//! chunk is already bounded by the MTU, large messages just span more of
+//! them.
+//!
+//! Gated behind the `codec` feature, since it's the only part of the crate
+//! that pulls in `tokio`/`tokio-util`/`futures-util`.
+use std::io;
+use std::marker::PhantomData;
+
+use bytes::{Buf, BufMut, BytesMut};
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::codec::{Decoder, Encoder, Framed};
+
+use crate::error::LuError;
+use crate::raknet::split::{split_message, Reassembler, SplitPacketHeader};
+use crate::registry::{MessageRegistry, RegistryCodec};
+
+/// RakNet's conventional UDP MTU payload size, used as the default chunk
+/// size for splitting oversized messages.
+const DEFAULT_MTU_PAYLOAD: usize = 1228;
+
+/// Byte length of the per-chunk header written ahead of each chunk's bytes:
+/// `split_packet_count: u32`, `split_packet_id: u16`, `split_packet_index: u32`,
+/// `chunk_len: u16`.
+const CHUNK_HEADER_LEN: usize = 4 + 2 + 4 + 2;
+
+/// Frames messages for use with [`tokio_util::codec::Framed`], splitting
+/// outgoing messages larger than `mtu_payload` into chunks and reassembling
+/// incoming chunks back into full messages.
+///
+/// `M` is the top-level message type for one side of a connection, e.g.
+/// [`ClientMessage`](crate::world::client::ClientMessage) or
+/// [`WorldMessage`](crate::world::server::WorldMessage). Decoding and
+/// encoding go through `M`'s [`RegistryCodec`] impl, so a [`MessageRegistry`]
+/// registered on this codec is consulted for every message, not just ones
+/// decoded by hand outside of this transport.
+pub struct MessageCodec<M: RegistryCodec> {
+	mtu_payload: usize,
+	next_split_id: u16,
+	reassembler: Reassembler,
+	registry: MessageRegistry<M>,
+	_msg: PhantomData<fn() -> M>,
+}
+
+impl<M: RegistryCodec> MessageCodec<M> {
+	/// Creates a new codec instance with an empty [`MessageRegistry`] and the
+	/// default MTU payload.
+	pub fn new() -> Self {
+		Self::with_mtu_payload(DEFAULT_MTU_PAYLOAD)
+	}
+
+	/// Creates a new codec instance that splits outgoing messages larger than
+	/// `mtu_payload` bytes into that many bytes per chunk.
+	pub fn with_mtu_payload(mtu_payload: usize) -> Self {
+		assert!(mtu_payload > 0 && mtu_payload <= u16::MAX as usize, "mtu_payload must be nonzero and fit in a u16");
+		Self {
+			mtu_payload,
+			next_split_id: 0,
+			reassembler: Reassembler::new(),
+			registry: MessageRegistry::default(),
+			_msg: PhantomData,
+		}
+	}
+
+	/// Creates a new codec instance that consults `registry` on every decode
+	/// and encode, using the default MTU payload.
+	pub fn with_registry(registry: MessageRegistry<M>) -> Self {
+		Self {
+			registry,
+			..Self::with_mtu_payload(DEFAULT_MTU_PAYLOAD)
+		}
+	}
+}
+
+impl<M: RegistryCodec> Default for MessageCodec<M> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<M: RegistryCodec> Decoder for MessageCodec<M>
+	where for<'a> u32: endio::Deserialize<endio::LittleEndian, &'a [u8]> {
+	/// The decoded message along with the byte length of the reassembled
+	/// message it came from, for callers (like [`Connection::recv`]) that
+	/// want to record it.
+	type Item = (M, usize);
+	type Error = io::Error;
+
+	fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<(M, usize)>> {
+		loop {
+			if src.len() < CHUNK_HEADER_LEN {
+				return Ok(None);
+			}
+			let split_packet_count = u32::from_le_bytes(src[0..4].try_into().unwrap());
+			let split_packet_id = u16::from_le_bytes(src[4..6].try_into().unwrap());
+			let split_packet_index = u32::from_le_bytes(src[6..10].try_into().unwrap());
+			let chunk_len = u16::from_le_bytes(src[10..12].try_into().unwrap()) as usize;
+			if src.len() < CHUNK_HEADER_LEN + chunk_len {
+				src.reserve(CHUNK_HEADER_LEN + chunk_len - src.len());
+				return Ok(None);
+			}
+			src.advance(CHUNK_HEADER_LEN);
+			let chunk = src.split_to(chunk_len);
+
+			let header = SplitPacketHeader { split_packet_count, split_packet_id, split_packet_index };
+			let full = match self.reassembler.insert(header, &chunk) {
+				Some(full) => full,
+				None => continue,
+			};
+
+			let len = full.len();
+			let mut cursor = &full[..];
+			let msg = M::decode_with_registry(&mut cursor, &self.registry)?;
+			if !cursor.is_empty() {
+				return Err(LuError::TrailingBytes.into());
+			}
+			return Ok(Some((msg, len)));
+		}
+	}
+}
+
+impl<'m, M: RegistryCodec> Encoder<&'m M> for MessageCodec<M>
+	where u32: endio::Serialize<endio::LittleEndian, Vec<u8>> {
+	type Error = io::Error;
+
+	fn encode(&mut self, msg: &'m M, dst: &mut BytesMut) -> io::Result<()> {
+		let mut buf = Vec::new();
+		msg.encode_with_registry(&mut buf, &self.registry)?;
+
+		let split_packet_id = self.next_split_id;
+		self.next_split_id = self.next_split_id.wrapping_add(1);
+		for (header, chunk) in split_message(&buf, split_packet_id, self.mtu_payload) {
+			dst.put_u32_le(header.split_packet_count);
+			dst.put_u16_le(header.split_packet_id);
+			dst.put_u32_le(header.split_packet_index);
+			dst.put_u16_le(chunk.len() as u16);
+			dst.put_slice(chunk);
+		}
+		Ok(())
+	}
+}
+
+/// A framed connection that decodes/encodes a stream of `M` messages,
+/// splitting/reassembling oversized ones transparently.
+///
+/// This is the ready-to-use transport the `codec` module exists to provide:
+/// construct one from an already-connected `AsyncRead + AsyncWrite` stream
+/// (e.g. a `tokio::net::TcpStream`) instead of reimplementing packet framing.
+pub struct Connection<S, M: RegistryCodec> {
+	framed: Framed<S, MessageCodec<M>>,
+	#[cfg(feature = "metrics")]
+	metrics: Option<std::sync::Arc<crate::metrics::Metrics>>,
+}
+
+impl<S, M: RegistryCodec> Connection<S, M>
+	where S: AsyncRead + AsyncWrite + Unpin,
+	      for<'a> u32: endio::Deserialize<endio::LittleEndian, &'a [u8]> {
+	/// Wraps an already-connected stream in the message framing.
+	pub fn new(stream: S) -> Self {
+		Self::with_codec(stream, MessageCodec::new())
+	}
+
+	/// Wraps an already-connected stream in the message framing, consulting
+	/// `registry` on every decode and encode.
+	pub fn with_registry(stream: S, registry: MessageRegistry<M>) -> Self {
+		Self::with_codec(stream, MessageCodec::with_registry(registry))
+	}
+
+	fn with_codec(stream: S, codec: MessageCodec<M>) -> Self {
+		Self {
+			framed: Framed::new(stream, codec),
+			#[cfg(feature = "metrics")]
+			metrics: None,
+		}
+	}
+
+	/// Wraps an already-connected stream in the message framing, recording
+	/// decode metrics to `metrics`.
+	#[cfg(feature = "metrics")]
+	pub fn with_metrics(stream: S, metrics: std::sync::Arc<crate::metrics::Metrics>) -> Self {
+		Self {
+			framed: Framed::new(stream, MessageCodec::new()),
+			metrics: Some(metrics),
+		}
+	}
+
+	/// Reads the next complete message, waiting for more data if necessary.
+	///
+	/// Returns an `UnexpectedEof` error once the stream ends.
+	#[cfg(not(feature = "metrics"))]
+	pub async fn recv(&mut self) -> io::Result<M> {
+		match self.framed.next().await {
+			Some(Ok((msg, _len))) => Ok(msg),
+			Some(Err(e)) => Err(e),
+			None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed")),
+		}
+	}
+
+	/// Reads the next complete message, waiting for more data if necessary.
+	///
+	/// Returns an `UnexpectedEof` error once the stream ends. Wraps the
+	/// decode in a `tracing` span and, if a [`Metrics`](crate::metrics::Metrics)
+	/// was configured, records it as decoded/rejected.
+	#[cfg(feature = "metrics")]
+	pub async fn recv(&mut self) -> io::Result<M>
+		where M: crate::metrics::VariantName {
+		let span = tracing::debug_span!("lu_packets::decode", variant = tracing::field::Empty);
+		let _guard = span.enter();
+		match self.framed.next().await {
+			Some(Ok((msg, len))) => {
+				span.record("variant", msg.variant_name());
+				if let Some(metrics) = &self.metrics {
+					metrics.record_decoded(msg.variant_name(), len);
+				}
+				Ok(msg)
+			}
+			Some(Err(e)) => {
+				if let Some(metrics) = &self.metrics {
+					metrics.record_rejected("unknown");
+				}
+				Err(e)
+			}
+			None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed")),
+		}
+	}
+}
+
+impl<S, M: RegistryCodec> Connection<S, M>
+	where S: AsyncRead + AsyncWrite + Unpin {
+	/// Serializes and sends a message, splitting it into chunks first if it
+	/// exceeds the codec's configured MTU payload.
+	pub async fn send<'m>(&mut self, msg: &'m M) -> io::Result<()>
+		where u32: endio::Serialize<endio::LittleEndian, Vec<u8>> {
+		self.framed.send(msg).await
+	}
+}
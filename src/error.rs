@@ -0,0 +1,76 @@
+//! Structured error type for packet (de)serialization failures.
+use std::fmt;
+use std::io;
+
+/// Errors that can occur while deserializing or serializing a packet.
+///
+/// Hand-written and derived `Deserialize`/`Serialize` impls across the crate
+/// construct these to describe exactly why a packet was rejected, then
+/// convert them into the `std::io::Error` their trait signatures require via
+/// [`From<LuError> for io::Error`](#impl-From<LuError>-for-Error). Use
+/// [`From<io::Error> for LuError`](#impl-From<io::Error>-for-LuError) (e.g.
+/// via `?`) to recover the structured variant on the way back out, falling
+/// back to [`LuError::Io`] for errors that didn't originate as a `LuError`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum LuError {
+	/// An enum discriminant that doesn't match any known variant.
+	UnknownVariant {
+		/// Name of the enum type that failed to match.
+		type_name: &'static str,
+		/// The raw discriminant value that was read off the wire.
+		discriminant: u32,
+	},
+	/// A length prefix didn't describe a valid amount of following data.
+	InvalidLength,
+	/// A byte sequence that was supposed to be UTF-16 couldn't be decoded.
+	Utf16Decode,
+	/// Bytes were left over after a message was fully parsed.
+	TrailingBytes,
+	/// An I/O error that isn't one of the above.
+	Io(io::Error),
+}
+
+impl fmt::Display for LuError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::UnknownVariant { type_name, discriminant } =>
+				write!(f, "unknown {} discriminant {}", type_name, discriminant),
+			Self::InvalidLength => write!(f, "invalid length prefix"),
+			Self::Utf16Decode => write!(f, "invalid utf-16 sequence"),
+			Self::TrailingBytes => write!(f, "trailing bytes after message"),
+			Self::Io(e) => write!(f, "{}", e),
+		}
+	}
+}
+
+impl std::error::Error for LuError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Io(e) => Some(e),
+			_ => None,
+		}
+	}
+}
+
+impl From<io::Error> for LuError {
+	fn from(e: io::Error) -> Self {
+		if e.get_ref().map_or(false, |inner| inner.is::<LuError>()) {
+			let inner = e.into_inner().unwrap();
+			return *inner.downcast::<LuError>().unwrap();
+		}
+		Self::Io(e)
+	}
+}
+
+impl From<LuError> for io::Error {
+	fn from(e: LuError) -> Self {
+		match e {
+			LuError::Io(e) => e,
+			e => io::Error::new(io::ErrorKind::InvalidData, e),
+		}
+	}
+}
+
+/// `Result` alias for fallible packet (de)serialization.
+pub type Result<T> = std::result::Result<T, LuError>;
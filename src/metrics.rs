@@ -0,0 +1,66 @@
+//! Optional Prometheus/`tracing` instrumentation for the (de)serialization path.
+//!
+//! Gated behind the `metrics` feature. Without it, decoding a packet is
+//! silent; with it, [`Connection`](crate::codec::Connection) records
+//! per-message success counters, a failure counter and a size histogram, and
+//! wraps each decode in a `tracing` span.
+use prometheus::{HistogramVec, IntCounterVec, Opts, Registry};
+
+/// Counters and histograms for the (de)serialization path.
+///
+/// Construct one with the [`Registry`] an existing exporter already serves,
+/// or read the individual metrics directly.
+pub struct Metrics {
+	/// Successfully decoded packets, labeled by resolved variant name.
+	pub decoded: IntCounterVec,
+	/// Packets rejected during decoding, labeled by the name passed to
+	/// [`Self::record_rejected`]. [`Connection`](crate::codec::Connection)
+	/// does not resolve the variant of a rejected packet and records every
+	/// rejection under `"unknown"`.
+	pub rejected: IntCounterVec,
+	/// Distribution of serialized message sizes in bytes, labeled by variant name.
+	pub size_bytes: HistogramVec,
+}
+
+impl Metrics {
+	/// Creates the metrics and registers them with `registry`.
+	pub fn new(registry: &Registry) -> prometheus::Result<Self> {
+		let decoded = IntCounterVec::new(
+			Opts::new("lu_packets_decoded_total", "Packets successfully decoded, by message variant"),
+			&["variant"],
+		)?;
+		let rejected = IntCounterVec::new(
+			Opts::new("lu_packets_rejected_total", "Packets rejected during decoding, by message variant"),
+			&["variant"],
+		)?;
+		let size_bytes = HistogramVec::new(
+			prometheus::HistogramOpts::new("lu_packets_size_bytes", "Serialized message size in bytes, by message variant"),
+			&["variant"],
+		)?;
+
+		registry.register(Box::new(decoded.clone()))?;
+		registry.register(Box::new(rejected.clone()))?;
+		registry.register(Box::new(size_bytes.clone()))?;
+
+		Ok(Self { decoded, rejected, size_bytes })
+	}
+
+	/// Records a successfully decoded packet of `size` bytes for `variant`.
+	pub fn record_decoded(&self, variant: &str, size: usize) {
+		self.decoded.with_label_values(&[variant]).inc();
+		self.size_bytes.with_label_values(&[variant]).observe(size as f64);
+	}
+
+	/// Records a packet that failed to decode as `variant`.
+	pub fn record_rejected(&self, variant: &str) {
+		self.rejected.with_label_values(&[variant]).inc();
+	}
+}
+
+/// Resolves the variant name of a decoded top-level message, for use as a
+/// metrics label and in the `tracing` span [`Connection`](crate::codec::Connection)
+/// wraps each decode in.
+pub trait VariantName {
+	/// Name of the variant this message decoded to, e.g. `"LoadStaticZone"`.
+	fn variant_name(&self) -> &'static str;
+}
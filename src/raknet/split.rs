@@ -0,0 +1,133 @@
+//! RakNet split-packet chunking and reassembly for messages too large to fit a single datagram.
+//!
+//! Registered as `mod split;` from the `raknet` module root. Large transfers
+//! like UGC downloads and blueprint data exceed a single datagram's MTU
+//! payload, so outgoing messages that are too big get broken into chunks
+//! here, and incoming chunks get collected back into the original payload
+//! before being handed to the normal [`Deserialize`](endio::Deserialize) path.
+use std::collections::HashMap;
+
+/// Generous upper bound on the size of a single reassembled message.
+const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Smallest chunk size worth splitting into; bounds [`MAX_SPLIT_PACKET_COUNT`]
+/// without assuming anything about the real `mtu_payload` a sender used.
+const MIN_CHUNK_SIZE: usize = 64;
+
+/// Largest `split_packet_count` [`Reassembler::insert`] will allocate space
+/// for. A chunk claiming a higher count is rejected outright, since
+/// allocating a `Vec<Option<Vec<u8>>>` sized by an attacker-controlled count
+/// before a single byte of real data has arrived is an easy way to OOM the
+/// process.
+const MAX_SPLIT_PACKET_COUNT: u32 = (MAX_MESSAGE_SIZE / MIN_CHUNK_SIZE) as u32;
+
+/// Largest number of distinct `split_packet_id`s [`Reassembler`] will track
+/// reassembly state for at once, bounding total memory use even if every
+/// pending message is small.
+const MAX_PENDING_SPLITS: usize = 64;
+
+/// Header carried by each chunk of a split message, alongside the usual
+/// reliability/ordering fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SplitPacketHeader {
+	pub split_packet_count: u32,
+	pub split_packet_id: u16,
+	pub split_packet_index: u32,
+}
+
+/// Splits `payload` into `mtu_payload`-sized chunks, each paired with the
+/// [`SplitPacketHeader`] it should be sent with.
+///
+/// An empty `payload` still yields a single empty chunk, so a
+/// [`Reassembler`] keyed on its `split_packet_id` sees the chunk its header
+/// promises instead of waiting forever for one that was never sent.
+pub fn split_message(payload: &[u8], split_packet_id: u16, mtu_payload: usize) -> Vec<(SplitPacketHeader, &[u8])> {
+	assert!(mtu_payload > 0, "mtu_payload must be nonzero");
+	let chunks: Vec<&[u8]> = if payload.is_empty() {
+		vec![payload]
+	} else {
+		payload.chunks(mtu_payload).collect()
+	};
+	let split_packet_count = chunks.len() as u32;
+	chunks
+		.into_iter()
+		.enumerate()
+		.map(|(index, chunk)| {
+			let header = SplitPacketHeader {
+				split_packet_count,
+				split_packet_id,
+				split_packet_index: index as u32,
+			};
+			(header, chunk)
+		})
+		.collect()
+}
+
+#[derive(Debug)]
+struct PendingSplit {
+	chunks: Vec<Option<Vec<u8>>>,
+	received: usize,
+}
+
+/// Reassembles chunks of one or more in-flight split messages, keyed by
+/// `split_packet_id`.
+///
+/// Chunks with an out-of-range `split_packet_index`, a `split_packet_count`
+/// of zero or above [`MAX_SPLIT_PACKET_COUNT`], or that would start tracking
+/// a new `split_packet_id` beyond [`MAX_PENDING_SPLITS`], are silently
+/// dropped. A duplicate index keeps whichever chunk arrived first.
+#[derive(Debug, Default)]
+pub struct Reassembler {
+	pending: HashMap<u16, PendingSplit>,
+}
+
+impl Reassembler {
+	/// Creates an empty reassembler.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Feeds one chunk in, returning the reassembled payload once every chunk
+	/// for its `split_packet_id` has arrived.
+	pub fn insert(&mut self, header: SplitPacketHeader, data: &[u8]) -> Option<Vec<u8>> {
+		if header.split_packet_count == 0 || header.split_packet_count > MAX_SPLIT_PACKET_COUNT {
+			return None;
+		}
+		let index = header.split_packet_index as usize;
+		if index >= header.split_packet_count as usize {
+			return None;
+		}
+		if !self.pending.contains_key(&header.split_packet_id) && self.pending.len() >= MAX_PENDING_SPLITS {
+			return None;
+		}
+
+		let pending = self.pending.entry(header.split_packet_id).or_insert_with(|| PendingSplit {
+			chunks: vec![None; header.split_packet_count as usize],
+			received: 0,
+		});
+		if pending.chunks.len() != header.split_packet_count as usize {
+			// split_packet_count changed mid-reassembly; restart from this chunk.
+			*pending = PendingSplit {
+				chunks: vec![None; header.split_packet_count as usize],
+				received: 0,
+			};
+		}
+
+		if pending.chunks[index].is_none() {
+			pending.chunks[index] = Some(data.to_vec());
+			pending.received += 1;
+		}
+
+		if pending.received < pending.chunks.len() {
+			return None;
+		}
+
+		let pending = self.pending.remove(&header.split_packet_id)?;
+		Some(pending.chunks.into_iter().flatten().flatten().collect())
+	}
+
+	/// Drops all incomplete reassembly buffers, e.g. on connection reset.
+	pub fn clear(&mut self) {
+		self.pending.clear();
+	}
+}
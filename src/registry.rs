@@ -0,0 +1,84 @@
+//! Opcode registry letting downstream crates extend message dispatch without
+//! forcing every new message into this crate.
+//!
+//! [`ClientMessage`](crate::world::client::ClientMessage) and
+//! [`WorldMessage`](crate::world::server::WorldMessage) only model the
+//! opcodes this crate knows about, falling back to an `Unknown` variant for
+//! everything else. A [`MessageRegistry`] lets a downstream crate register
+//! its own decoder/encoder for a given opcode, consulted before that
+//! fallback, turning the enums from a closed set into an extensible
+//! dispatch table.
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+use crate::error::Result;
+
+type DecodeFn<M> = Box<dyn Fn(&[u8]) -> Result<M> + Send + Sync>;
+type EncodeFn<M> = Box<dyn Fn(&M, &mut Vec<u8>) -> Result<()> + Send + Sync>;
+
+/// A registry of opcode decoders/encoders for messages not modeled natively
+/// by this crate's `ClientMessage`/`WorldMessage` enums.
+pub struct MessageRegistry<M> {
+	handlers: HashMap<u32, (DecodeFn<M>, EncodeFn<M>)>,
+}
+
+impl<M> MessageRegistry<M> {
+	/// Creates an empty registry.
+	pub fn new() -> Self {
+		Self { handlers: HashMap::new() }
+	}
+
+	/// Registers a decoder/encoder pair for `opcode`.
+	///
+	/// Replaces any handler previously registered for the same opcode.
+	pub fn register(
+		&mut self,
+		opcode: u32,
+		decode: impl Fn(&[u8]) -> Result<M> + Send + Sync + 'static,
+		encode: impl Fn(&M, &mut Vec<u8>) -> Result<()> + Send + Sync + 'static,
+	) {
+		self.handlers.insert(opcode, (Box::new(decode), Box::new(encode)));
+	}
+
+	/// Removes the handler registered for `opcode`, if any.
+	pub fn unregister(&mut self, opcode: u32) {
+		self.handlers.remove(&opcode);
+	}
+
+	/// Decodes `body` using the handler registered for `opcode`, if any.
+	pub fn decode(&self, opcode: u32, body: &[u8]) -> Option<Result<M>> {
+		self.handlers.get(&opcode).map(|(decode, _)| decode(body))
+	}
+
+	/// Encodes `msg` into `out` using the handler registered for `opcode`, if any.
+	pub fn encode(&self, opcode: u32, msg: &M, out: &mut Vec<u8>) -> Option<Result<()>> {
+		self.handlers.get(&opcode).map(|(_, encode)| encode(msg, out))
+	}
+
+	/// Whether a handler is registered for `opcode`.
+	pub fn contains(&self, opcode: u32) -> bool {
+		self.handlers.contains_key(&opcode)
+	}
+}
+
+impl<M> Default for MessageRegistry<M> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// Implemented by message enums whose `deserialize_with_registry`/
+/// `serialize_with_registry` inherent methods consult a [`MessageRegistry`]
+/// before falling back to their own `Unknown` variant.
+///
+/// Lets generic transports like [`MessageCodec`](crate::codec::MessageCodec)
+/// decode/encode through the registry without hardcoding a concrete message
+/// enum.
+pub trait RegistryCodec: Sized {
+	/// Forwards to the implementing enum's `deserialize_with_registry`.
+	fn decode_with_registry<R: Read + endio::LERead>(reader: &mut R, registry: &MessageRegistry<Self>) -> std::io::Result<Self>
+		where u32: endio::Deserialize<endio::LittleEndian, R>;
+	/// Forwards to the implementing enum's `serialize_with_registry`.
+	fn encode_with_registry<W: Write + endio::LEWrite>(&self, writer: &mut W, registry: &MessageRegistry<Self>) -> std::io::Result<()>
+		where u32: endio::Serialize<endio::LittleEndian, W>;
+}
@@ -1,11 +1,12 @@
 //! Client-received world messages.
+use std::io::{Read, Write};
 use std::io::Result as Res;
 
 use endio::{Deserialize, LERead, LEWrite, Serialize};
 use endio::LittleEndian as LE;
-use lu_packets_derive::{FromVariants, VariantTests};
 
 use crate::common::{ObjId, LuString33, LuWString33, LVec};
+use crate::registry::MessageRegistry;
 use super::{Lot, lnv::LuNameValue, Vector3, ZoneId};
 use super::gm::client::SubjectGameMessage;
 
@@ -21,9 +22,14 @@ impl From<ClientMessage> for Message {
 }
 
 /// All client-received world messages.
-#[derive(Debug, Deserialize, PartialEq, Serialize, FromVariants, VariantTests)]
+///
+/// Hand-implements `Deserialize`/`Serialize` instead of deriving them: a
+/// fixed discriminant match can't fall back to [`Self::Unknown`] for an
+/// opcode it doesn't recognize, which is the whole point of that variant.
+/// The discriminants below are carried only by this match, not by the
+/// generated code a plain `#[repr(u32)]` derive would produce.
+#[derive(Debug, PartialEq)]
 #[non_exhaustive]
-#[post_disc_padding=1]
 #[repr(u32)]
 pub enum ClientMessage {
 	LoadStaticZone(LoadStaticZone) = 2,
@@ -39,6 +45,170 @@ pub enum ClientMessage {
 	MinimumChatModeResponse(MinimumChatModeResponse) = 57,
 	MinimumChatModeResponsePrivate(MinimumChatModeResponsePrivate) = 58,
 	UpdateFreeTrialStatus(UpdateFreeTrialStatus) = 62,
+	/// Catch-all for opcodes not modeled above.
+	///
+	/// Carries the raw, still-serialized message body so a proxy or analytics
+	/// tool can pass the packet through (or inspect it) instead of failing to
+	/// parse it outright. [`ClientMessage::deserialize_with_registry`] checks
+	/// a [`MessageRegistry`] for the opcode before falling back to this variant.
+	Unknown { opcode: u32, body: Vec<u8> },
+}
+
+impl ClientMessage {
+	/// Matches `opcode` against the known discriminants above, falling back
+	/// to [`Self::Unknown`] with the already-consumed `body` bytes.
+	fn decode_body(opcode: u32, body: Vec<u8>) -> Res<Self> {
+		Ok(match opcode {
+			2 => Self::LoadStaticZone((&body[..]).read()?),
+			4 => Self::CreateCharacter((&body[..]).read()?),
+			6 => Self::CharacterListResponse((&body[..]).read()?),
+			7 => Self::CharacterCreateResponse((&body[..]).read()?),
+			11 => Self::CharacterDeleteResponse((&body[..]).read()?),
+			12 => Self::SubjectGameMessage((&body[..]).read()?),
+			14 => Self::TransferToWorld((&body[..]).read()?),
+			23 => Self::BlueprintLoadItemResponse((&body[..]).read()?),
+			27 => Self::AddFriendRequest((&body[..]).read()?),
+			35 => Self::TeamInvite((&body[..]).read()?),
+			57 => Self::MinimumChatModeResponse((&body[..]).read()?),
+			58 => Self::MinimumChatModeResponsePrivate((&body[..]).read()?),
+			62 => Self::UpdateFreeTrialStatus((&body[..]).read()?),
+			_ => Self::Unknown { opcode, body },
+		})
+	}
+
+	/// Deserializes a message, checking `registry` for a handler registered
+	/// for the opcode before falling back to the discriminants modeled by
+	/// this crate and, ultimately, [`Self::Unknown`].
+	pub fn deserialize_with_registry<R: Read+LERead>(reader: &mut R, registry: &MessageRegistry<Self>) -> Res<Self>
+		where u32: Deserialize<LE, R> {
+		let opcode: u32 = reader.read()?;
+		let _padding: u8 = reader.read()?;
+		let mut body = Vec::new();
+		reader.read_to_end(&mut body)?;
+		if let Some(result) = registry.decode(opcode, &body) {
+			return result.map_err(Into::into);
+		}
+		Self::decode_body(opcode, body)
+	}
+
+	/// Serializes a message, checking `registry` for a handler registered
+	/// for [`Self::Unknown`]'s opcode instead of writing its captured body
+	/// back out verbatim.
+	pub fn serialize_with_registry<W: Write+LEWrite>(&self, writer: &mut W, registry: &MessageRegistry<Self>) -> Res<()>
+		where u32: Serialize<LE, W> {
+		if let Self::Unknown { opcode, .. } = self {
+			let mut encoded = Vec::new();
+			if let Some(result) = registry.encode(*opcode, self, &mut encoded) {
+				result.map_err(Into::<std::io::Error>::into)?;
+				writer.write(*opcode)?;
+				writer.write(0u8)?;
+				return writer.write_all(&encoded);
+			}
+		}
+		writer.write(self)
+	}
+}
+
+impl<R: Read+LERead> Deserialize<LE, R> for ClientMessage
+	where u32: Deserialize<LE, R> {
+	fn deserialize(reader: &mut R) -> Res<Self> {
+		let opcode: u32 = reader.read()?;
+		let _padding: u8 = reader.read()?;
+		let mut body = Vec::new();
+		reader.read_to_end(&mut body)?;
+		Self::decode_body(opcode, body)
+	}
+}
+
+impl<'a, W: Write+LEWrite> Serialize<LE, W> for &'a ClientMessage
+	where u32: Serialize<LE, W> {
+	fn serialize(self, writer: &mut W) -> Res<()> {
+		macro_rules! write_variant {
+			($opcode:expr, $msg:expr) => {{
+				writer.write($opcode as u32)?;
+				writer.write(0u8)?;
+				writer.write($msg)
+			}};
+		}
+		match self {
+			ClientMessage::LoadStaticZone(msg) => write_variant!(2, msg),
+			ClientMessage::CreateCharacter(msg) => write_variant!(4, msg),
+			ClientMessage::CharacterListResponse(msg) => write_variant!(6, msg),
+			ClientMessage::CharacterCreateResponse(msg) => write_variant!(7, msg),
+			ClientMessage::CharacterDeleteResponse(msg) => write_variant!(11, msg),
+			ClientMessage::SubjectGameMessage(msg) => write_variant!(12, msg),
+			ClientMessage::TransferToWorld(msg) => write_variant!(14, msg),
+			ClientMessage::BlueprintLoadItemResponse(msg) => write_variant!(23, msg),
+			ClientMessage::AddFriendRequest(msg) => write_variant!(27, msg),
+			ClientMessage::TeamInvite(msg) => write_variant!(35, msg),
+			ClientMessage::MinimumChatModeResponse(msg) => write_variant!(57, msg),
+			ClientMessage::MinimumChatModeResponsePrivate(msg) => write_variant!(58, msg),
+			ClientMessage::UpdateFreeTrialStatus(msg) => write_variant!(62, msg),
+			ClientMessage::Unknown { opcode, body } => {
+				writer.write(*opcode)?;
+				writer.write(0u8)?;
+				writer.write_all(body)
+			}
+		}
+	}
+}
+
+macro_rules! impl_from_variant {
+	($variant:ident, $ty:ty) => {
+		impl From<$ty> for ClientMessage {
+			fn from(msg: $ty) -> Self {
+				Self::$variant(msg)
+			}
+		}
+	};
+}
+
+impl_from_variant!(LoadStaticZone, LoadStaticZone);
+impl_from_variant!(CreateCharacter, CreateCharacter);
+impl_from_variant!(CharacterListResponse, CharacterListResponse);
+impl_from_variant!(CharacterCreateResponse, CharacterCreateResponse);
+impl_from_variant!(CharacterDeleteResponse, CharacterDeleteResponse);
+impl_from_variant!(SubjectGameMessage, SubjectGameMessage);
+impl_from_variant!(TransferToWorld, TransferToWorld);
+impl_from_variant!(BlueprintLoadItemResponse, BlueprintLoadItemResponse);
+impl_from_variant!(AddFriendRequest, AddFriendRequest);
+impl_from_variant!(TeamInvite, TeamInvite);
+impl_from_variant!(MinimumChatModeResponse, MinimumChatModeResponse);
+impl_from_variant!(MinimumChatModeResponsePrivate, MinimumChatModeResponsePrivate);
+impl_from_variant!(UpdateFreeTrialStatus, UpdateFreeTrialStatus);
+
+impl crate::registry::RegistryCodec for ClientMessage {
+	fn decode_with_registry<R: Read+LERead>(reader: &mut R, registry: &MessageRegistry<Self>) -> Res<Self>
+		where u32: Deserialize<LE, R> {
+		Self::deserialize_with_registry(reader, registry)
+	}
+
+	fn encode_with_registry<W: Write+LEWrite>(&self, writer: &mut W, registry: &MessageRegistry<Self>) -> Res<()>
+		where u32: Serialize<LE, W> {
+		self.serialize_with_registry(writer, registry)
+	}
+}
+
+#[cfg(feature = "metrics")]
+impl crate::metrics::VariantName for ClientMessage {
+	fn variant_name(&self) -> &'static str {
+		match self {
+			Self::LoadStaticZone(_) => "LoadStaticZone",
+			Self::CreateCharacter(_) => "CreateCharacter",
+			Self::CharacterListResponse(_) => "CharacterListResponse",
+			Self::CharacterCreateResponse(_) => "CharacterCreateResponse",
+			Self::CharacterDeleteResponse(_) => "CharacterDeleteResponse",
+			Self::SubjectGameMessage(_) => "SubjectGameMessage",
+			Self::TransferToWorld(_) => "TransferToWorld",
+			Self::BlueprintLoadItemResponse(_) => "BlueprintLoadItemResponse",
+			Self::AddFriendRequest(_) => "AddFriendRequest",
+			Self::TeamInvite(_) => "TeamInvite",
+			Self::MinimumChatModeResponse(_) => "MinimumChatModeResponse",
+			Self::MinimumChatModeResponsePrivate(_) => "MinimumChatModeResponsePrivate",
+			Self::UpdateFreeTrialStatus(_) => "UpdateFreeTrialStatus",
+			Self::Unknown { .. } => "Unknown",
+		}
+	}
 }
 
 #[derive(Debug, Deserialize, PartialEq, Serialize)]
@@ -308,3 +478,54 @@ pub struct UpdateFreeTrialStatus {
 	/// Whether the player is on free trial.
 	pub is_free_trial: bool,
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::registry::MessageRegistry;
+
+	/// An opcode not matched by `ClientMessage::decode_body` should round-trip
+	/// through `Unknown` with its body preserved byte-for-byte, including the
+	/// padding byte that follows every opcode on this side of the connection.
+	#[test]
+	fn client_message_unknown_round_trips() {
+		let msg = ClientMessage::Unknown { opcode: 0xbeef, body: vec![1, 2, 3, 4, 5] };
+
+		let mut bytes = Vec::new();
+		bytes.write(&msg).unwrap();
+
+		let decoded: ClientMessage = (&bytes[..]).read().unwrap();
+		assert_eq!(decoded, msg);
+	}
+
+	/// A handler registered in the [`MessageRegistry`] must be consulted
+	/// before falling back to `Unknown`, for both decode and encode.
+	#[test]
+	fn client_message_registry_consulted_before_unknown() {
+		let opcode = 0xdead_u32;
+		let mut registry = MessageRegistry::new();
+		registry.register(
+			opcode,
+			// The handlers shift each byte so their output differs from what the
+			// plain `Unknown` fallback would produce.
+			move |body| Ok(ClientMessage::Unknown { opcode, body: body.iter().map(|b| b + 1).collect() }),
+			|msg, out| {
+				if let ClientMessage::Unknown { body, .. } = msg {
+					out.extend(body.iter().map(|b| b - 1));
+				}
+				Ok(())
+			},
+		);
+
+		let raw = ClientMessage::Unknown { opcode, body: vec![9, 8, 7] };
+		let mut bytes = Vec::new();
+		bytes.write(&raw).unwrap();
+
+		let msg = ClientMessage::deserialize_with_registry(&mut &bytes[..], &registry).unwrap();
+		assert_eq!(msg, ClientMessage::Unknown { opcode, body: vec![10, 9, 8] });
+
+		let mut out = Vec::new();
+		msg.serialize_with_registry(&mut out, &registry).unwrap();
+		assert_eq!(out, bytes);
+	}
+}
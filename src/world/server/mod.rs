@@ -6,10 +6,11 @@ use std::io::Result as Res;
 
 use endio::{Deserialize, LERead, LEWrite, Serialize};
 use endio::LittleEndian as LE;
-use lu_packets_derive::ServiceMessage;
 
-use crate::common::{err, ObjId, LuWStr33, LuWStr42, ServiceId, ZoneId};
+use crate::common::{ObjId, LuWStr33, LuWStr42, ServiceId, ZoneId};
 use crate::chat::server::ChatMessage;
+use crate::error::LuError;
+use crate::registry::MessageRegistry;
 use self::gm::SubjectGameMessage;
 
 pub use crate::general::server::GeneralMessage;
@@ -24,7 +25,11 @@ pub enum LuMessage {
 	World(WorldMessage) = ServiceId::World as u16,
 }
 
-#[derive(Debug, ServiceMessage)]
+/// Hand-implements `Deserialize`/`Serialize` instead of deriving them via
+/// `ServiceMessage`: a fixed discriminant match can't fall back to
+/// [`Self::Unknown`] for an opcode it doesn't recognize, which is the whole
+/// point of that variant.
+#[derive(Debug)]
 #[repr(u32)]
 pub enum WorldMessage {
 	ClientValidation(ClientValidation) = 1,
@@ -39,6 +44,141 @@ pub enum WorldMessage {
 	StringCheck(StringCheck) = 25,
 	RequestFreeTrialRefresh = 32,
 	UgcDownloadFailed(UgcDownloadFailed) = 120,
+	/// Catch-all for opcodes not modeled above.
+	///
+	/// Carries the raw, still-serialized message body so a proxy or analytics
+	/// tool can pass the packet through (or inspect it) instead of failing to
+	/// parse it outright. [`WorldMessage::deserialize_with_registry`] checks
+	/// a [`MessageRegistry`] for the opcode before falling back to this variant.
+	Unknown { opcode: u32, body: Vec<u8> },
+}
+
+impl WorldMessage {
+	/// Matches `opcode` against the known discriminants above, falling back
+	/// to [`Self::Unknown`] with the already-consumed `body` bytes.
+	fn decode_body(opcode: u32, body: Vec<u8>) -> Res<Self> {
+		Ok(match opcode {
+			1 => Self::ClientValidation((&body[..]).read()?),
+			2 => Self::CharacterListRequest,
+			3 => Self::CharacterCreateRequest((&body[..]).read()?),
+			4 => Self::CharacterLoginRequest((&body[..]).read()?),
+			5 => Self::SubjectGameMessage((&body[..]).read()?),
+			6 => Self::CharacterDeleteRequest((&body[..]).read()?),
+			14 => Self::GeneralChatMessage((&body[..]).read()?),
+			19 => Self::LevelLoadComplete((&body[..]).read()?),
+			21 => Self::RouteMessage((&body[..]).read()?),
+			25 => Self::StringCheck((&body[..]).read()?),
+			32 => Self::RequestFreeTrialRefresh,
+			120 => Self::UgcDownloadFailed((&body[..]).read()?),
+			_ => Self::Unknown { opcode, body },
+		})
+	}
+
+	/// Deserializes a message, checking `registry` for a handler registered
+	/// for the opcode before falling back to the discriminants modeled by
+	/// this crate and, ultimately, [`Self::Unknown`].
+	pub fn deserialize_with_registry<R: Read+LERead>(reader: &mut R, registry: &MessageRegistry<Self>) -> Res<Self>
+		where u32: Deserialize<LE, R> {
+		let opcode: u32 = reader.read()?;
+		let mut body = Vec::new();
+		reader.read_to_end(&mut body)?;
+		if let Some(result) = registry.decode(opcode, &body) {
+			return result.map_err(Into::into);
+		}
+		Self::decode_body(opcode, body)
+	}
+
+	/// Serializes a message, checking `registry` for a handler registered
+	/// for [`Self::Unknown`]'s opcode instead of writing its captured body
+	/// back out verbatim.
+	pub fn serialize_with_registry<W: Write+LEWrite>(&self, writer: &mut W, registry: &MessageRegistry<Self>) -> Res<()>
+		where u32: Serialize<LE, W> {
+		if let Self::Unknown { opcode, .. } = self {
+			let mut encoded = Vec::new();
+			if let Some(result) = registry.encode(*opcode, self, &mut encoded) {
+				result.map_err(Into::<std::io::Error>::into)?;
+				writer.write(*opcode)?;
+				return writer.write_all(&encoded);
+			}
+		}
+		writer.write(self)
+	}
+}
+
+impl<R: Read+LERead> Deserialize<LE, R> for WorldMessage
+	where u32: Deserialize<LE, R> {
+	fn deserialize(reader: &mut R) -> Res<Self> {
+		let opcode: u32 = reader.read()?;
+		let mut body = Vec::new();
+		reader.read_to_end(&mut body)?;
+		Self::decode_body(opcode, body)
+	}
+}
+
+impl<'a, W: Write+LEWrite> Serialize<LE, W> for &'a WorldMessage
+	where u32: Serialize<LE, W> {
+	fn serialize(self, writer: &mut W) -> Res<()> {
+		macro_rules! write_variant {
+			($opcode:expr, $msg:expr) => {{
+				writer.write($opcode as u32)?;
+				writer.write($msg)
+			}};
+			($opcode:expr) => {{
+				writer.write($opcode as u32)
+			}};
+		}
+		match self {
+			WorldMessage::ClientValidation(msg) => write_variant!(1, msg),
+			WorldMessage::CharacterListRequest => write_variant!(2),
+			WorldMessage::CharacterCreateRequest(msg) => write_variant!(3, msg),
+			WorldMessage::CharacterLoginRequest(msg) => write_variant!(4, msg),
+			WorldMessage::SubjectGameMessage(msg) => write_variant!(5, msg),
+			WorldMessage::CharacterDeleteRequest(msg) => write_variant!(6, msg),
+			WorldMessage::GeneralChatMessage(msg) => write_variant!(14, msg),
+			WorldMessage::LevelLoadComplete(msg) => write_variant!(19, msg),
+			WorldMessage::RouteMessage(msg) => write_variant!(21, msg),
+			WorldMessage::StringCheck(msg) => write_variant!(25, msg),
+			WorldMessage::RequestFreeTrialRefresh => write_variant!(32),
+			WorldMessage::UgcDownloadFailed(msg) => write_variant!(120, msg),
+			WorldMessage::Unknown { opcode, body } => {
+				writer.write(*opcode)?;
+				writer.write_all(body)
+			}
+		}
+	}
+}
+
+impl crate::registry::RegistryCodec for WorldMessage {
+	fn decode_with_registry<R: Read+LERead>(reader: &mut R, registry: &MessageRegistry<Self>) -> Res<Self>
+		where u32: Deserialize<LE, R> {
+		Self::deserialize_with_registry(reader, registry)
+	}
+
+	fn encode_with_registry<W: Write+LEWrite>(&self, writer: &mut W, registry: &MessageRegistry<Self>) -> Res<()>
+		where u32: Serialize<LE, W> {
+		self.serialize_with_registry(writer, registry)
+	}
+}
+
+#[cfg(feature = "metrics")]
+impl crate::metrics::VariantName for WorldMessage {
+	fn variant_name(&self) -> &'static str {
+		match self {
+			Self::ClientValidation(_) => "ClientValidation",
+			Self::CharacterListRequest => "CharacterListRequest",
+			Self::CharacterCreateRequest(_) => "CharacterCreateRequest",
+			Self::CharacterLoginRequest(_) => "CharacterLoginRequest",
+			Self::SubjectGameMessage(_) => "SubjectGameMessage",
+			Self::CharacterDeleteRequest(_) => "CharacterDeleteRequest",
+			Self::GeneralChatMessage(_) => "GeneralChatMessage",
+			Self::LevelLoadComplete(_) => "LevelLoadComplete",
+			Self::RouteMessage(_) => "RouteMessage",
+			Self::StringCheck(_) => "StringCheck",
+			Self::RequestFreeTrialRefresh => "RequestFreeTrialRefresh",
+			Self::UgcDownloadFailed(_) => "UgcDownloadFailed",
+			Self::Unknown { .. } => "Unknown",
+		}
+	}
 }
 
 #[derive(Debug)]
@@ -181,11 +321,13 @@ impl<R: Read+LERead> Deserialize<LE, R> for GeneralChatMessage
 		let chat_channel    = LERead::read(reader)?;
 		let source_id       = LERead::read(reader)?;
 		let string_len: u32 = LERead::read(reader)?;
-		let mut string = vec![0; (string_len*2) as usize];
-		let mut taken = Read::take(reader, (string_len*2) as u64);
-		Read::read(&mut taken, &mut string)?;
-		let string_slice: &[u16] = unsafe { std::slice::from_raw_parts(string.as_ptr() as *const u16, string_len as usize - 1) };
-		let message = String::from_utf16_lossy(string_slice);
+		let mut bytes = vec![0; (string_len*2) as usize];
+		Read::read_exact(reader, &mut bytes)?;
+		let units: Vec<u16> = bytes.chunks_exact(2)
+			.take((string_len as usize).saturating_sub(1))
+			.map(|b| u16::from_le_bytes([b[0], b[1]]))
+			.collect();
+		let message = String::from_utf16(&units).map_err(|_| LuError::Utf16Decode)?;
 
 		Ok(Self { chat_channel, source_id, message })
 	}
@@ -214,7 +356,10 @@ impl<R: LERead> Deserialize<LE, R> for RouteMessage
 				Self::Chat(reader.read()?)
 			}
 			_ => {
-				return err("route service id", service_id);
+				return Err(LuError::UnknownVariant {
+					type_name: "RouteMessage",
+					discriminant: service_id as u32,
+				}.into());
 			}
 		})
 	}
@@ -251,11 +396,12 @@ impl<R: Read+LERead> Deserialize<LE, R> for StringCheck
 		let chat_channel    = LERead::read(reader)?;
 		let recipient_name  = LERead::read(reader)?;
 		let string_len: u16 = LERead::read(reader)?;
-		let mut string = vec![0; (string_len*2) as usize];
-		let mut taken = Read::take(reader, (string_len*2) as u64);
-		Read::read(&mut taken, &mut string)?;
-		let string_slice: &[u16] = unsafe { std::slice::from_raw_parts(string.as_ptr() as *const u16, string_len as usize) };
-		let string = String::from_utf16_lossy(string_slice);
+		let mut bytes = vec![0; (string_len*2) as usize];
+		Read::read_exact(reader, &mut bytes)?;
+		let units: Vec<u16> = bytes.chunks_exact(2)
+			.map(|b| u16::from_le_bytes([b[0], b[1]]))
+			.collect();
+		let string = String::from_utf16(&units).map_err(|_| LuError::Utf16Decode)?;
 
 		Ok(Self { chat_mode, chat_channel, recipient_name, string })
 	}
@@ -292,4 +438,87 @@ pub struct UgcDownloadFailed {
 	pub blueprint_id: ObjId,
 	pub status_code: u32,
 	pub char_id: ObjId,
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::registry::MessageRegistry;
+
+	/// An opcode not matched by `WorldMessage::decode_body` should round-trip
+	/// through `Unknown` with its body preserved byte-for-byte.
+	#[test]
+	fn world_message_unknown_round_trips() {
+		let opcode = 0xbeef_u32;
+		let body = vec![1u8, 2, 3, 4, 5];
+		let mut bytes = Vec::new();
+		bytes.extend_from_slice(&opcode.to_le_bytes());
+		bytes.extend_from_slice(&body);
+
+		let msg: WorldMessage = (&bytes[..]).read().unwrap();
+		match &msg {
+			WorldMessage::Unknown { opcode: o, body: b } => {
+				assert_eq!(*o, opcode);
+				assert_eq!(b, &body);
+			}
+			_ => panic!("expected Unknown, got {:?}", msg),
+		}
+
+		let mut out = Vec::new();
+		out.write(&msg).unwrap();
+		assert_eq!(out, bytes);
+	}
+
+	/// A handler registered in the [`MessageRegistry`] must be consulted
+	/// before falling back to `Unknown`, for both decode and encode.
+	#[test]
+	fn world_message_registry_consulted_before_unknown() {
+		let opcode = 0xdead_u32;
+		let mut registry = MessageRegistry::new();
+		registry.register(
+			opcode,
+			// The handlers shift each byte so their output differs from what the
+			// plain `Unknown` fallback would produce.
+			move |body| Ok(WorldMessage::Unknown { opcode, body: body.iter().map(|b| b + 1).collect() }),
+			|msg, out| {
+				if let WorldMessage::Unknown { body, .. } = msg {
+					out.extend(body.iter().map(|b| b - 1));
+				}
+				Ok(())
+			},
+		);
+
+		let body = vec![9u8, 8, 7];
+		let mut bytes = Vec::new();
+		bytes.extend_from_slice(&opcode.to_le_bytes());
+		bytes.extend_from_slice(&body);
+
+		let msg = WorldMessage::deserialize_with_registry(&mut &bytes[..], &registry).unwrap();
+		match &msg {
+			WorldMessage::Unknown { opcode: o, body: b } => {
+				assert_eq!(*o, opcode);
+				assert_eq!(b, &vec![10u8, 9, 8]);
+			}
+			_ => panic!("expected Unknown, got {:?}", msg),
+		}
+
+		let mut out = Vec::new();
+		msg.serialize_with_registry(&mut out, &registry).unwrap();
+		assert_eq!(out, bytes);
+	}
+
+	/// Regression test for a `string_len` of zero: previously panicked with a
+	/// subtract overflow in debug builds instead of yielding an empty message.
+	#[test]
+	fn general_chat_message_zero_length_does_not_panic() {
+		let mut bytes = Vec::new();
+		bytes.push(3u8); // chat_channel
+		bytes.extend_from_slice(&42u16.to_le_bytes()); // source_id
+		bytes.extend_from_slice(&0u32.to_le_bytes()); // string_len
+
+		let msg: GeneralChatMessage = (&bytes[..]).read().unwrap();
+		assert_eq!(msg.chat_channel, 3);
+		assert_eq!(msg.source_id, 42);
+		assert_eq!(msg.message, "");
+	}
 }
\ No newline at end of file